@@ -0,0 +1,113 @@
+//! Extracts submitted CAPTCHA credentials from a multipart form, a JSON or
+//! url-encoded body, or request headers.
+
+use std::collections::HashMap;
+
+use axum::async_trait;
+use axum::extract::{FromRequest, Multipart, Request};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Form, Json};
+
+use crate::providers;
+
+#[derive(Debug, Default, Clone)]
+pub struct Credentials {
+    pub captcha_response: Option<String>,
+    pub captcha_token: Option<String>,
+    pub captcha_answer: Option<String>,
+    pub request_token: Option<String>,
+    pub redirect_url: Option<String>,
+}
+
+impl Credentials {
+    fn assign_field(&mut self, field_name: &str, value: String, response_field_name: Option<&str>) {
+        if value.is_empty() {
+            return;
+        }
+
+        if field_name == "captcha-response" || Some(field_name) == response_field_name {
+            self.captcha_response = Some(value);
+            return;
+        }
+
+        match field_name {
+            "captcha-token" => self.captcha_token = Some(value),
+            "captcha-answer" => self.captcha_answer = Some(value),
+            "request-token" => self.request_token = Some(value),
+            "redirect-url" => self.redirect_url = Some(value),
+            _ => {}
+        }
+    }
+
+    fn apply_header_overrides(&mut self, headers: &HeaderMap) {
+        if let Some(value) = header_value(headers, "x-captcha-response") {
+            self.captcha_response = Some(value);
+        }
+        if let Some(value) = header_value(headers, "x-captcha-token") {
+            self.captcha_token = Some(value);
+        }
+        if let Some(value) = header_value(headers, "x-captcha-answer") {
+            self.captcha_answer = Some(value);
+        }
+        if let Some(value) = header_value(headers, "x-request-token") {
+            self.request_token = Some(value);
+        }
+        if let Some(value) = header_value(headers, "x-redirect-url") {
+            self.redirect_url = Some(value);
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequest<S> for Credentials {
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let headers = req.headers().clone();
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        let response_field_name = providers::active_response_field_name();
+
+        let mut credentials = Credentials::default();
+
+        if content_type.starts_with("multipart/form-data") {
+            let mut multipart = Multipart::from_request(req, state)
+                .await
+                .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into_response())?;
+
+            while let Ok(Some(field)) = multipart.next_field().await {
+                let field_name = field.name().unwrap_or("").to_lowercase();
+                let value = field.text().await.unwrap_or_default();
+                credentials.assign_field(&field_name, value, response_field_name);
+            }
+        } else if content_type.starts_with("application/json") {
+            let Json(fields) = Json::<HashMap<String, String>>::from_request(req, state)
+                .await
+                .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into_response())?;
+            for (field_name, value) in fields {
+                credentials.assign_field(&field_name.to_lowercase(), value, response_field_name);
+            }
+        } else if content_type.starts_with("application/x-www-form-urlencoded") {
+            let Form(fields) = Form::<HashMap<String, String>>::from_request(req, state)
+                .await
+                .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into_response())?;
+            for (field_name, value) in fields {
+                credentials.assign_field(&field_name.to_lowercase(), value, response_field_name);
+            }
+        }
+
+        // Header-supplied credentials take precedence, so API clients that
+        // drive everything through headers don't also need to shape a body.
+        credentials.apply_header_overrides(&headers);
+
+        Ok(credentials)
+    }
+}
+
+fn header_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers.get(name).and_then(|v| v.to_str().ok()).filter(|v| !v.is_empty()).map(|v| v.to_string())
+}
@@ -0,0 +1,54 @@
+//! Self-hosted image CAPTCHA provider: renders a distorted-text challenge
+//! and checks answers via [`crate::storage`].
+
+use std::time::Duration;
+
+use base64::prelude::*;
+use captcha::filters::{Noise, Wave};
+use captcha::Captcha;
+
+use crate::storage;
+
+/// How long an issued challenge answer stays valid before it is swept.
+const CHALLENGE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Number of characters in the generated answer.
+const ANSWER_LENGTH: u32 = 6;
+
+/// A freshly generated challenge: the opaque token handed to the client and
+/// the rendered PNG image, already encoded as a `data:` URI.
+pub struct Challenge {
+    pub token: String,
+    pub image_data_uri: String,
+}
+
+/// Render a new distorted-text challenge, remembering its answer under a
+/// random token so that [`verify_and_consume`] can later check it.
+pub async fn generate_challenge() -> anyhow::Result<Challenge> {
+    let mut captcha = Captcha::new();
+    captcha.add_chars(ANSWER_LENGTH);
+    captcha.apply_filter(Noise::new(0.4));
+    captcha.apply_filter(Wave::new(2.0, 3.0));
+    captcha.view(220, 120);
+
+    let (answer, png) = captcha
+        .as_tuple()
+        .ok_or_else(|| anyhow::anyhow!("failed to render captcha image"))?;
+
+    let token = storage::random_token();
+    storage::STORAGE.store(&token, &answer.to_lowercase(), CHALLENGE_TTL).await?;
+
+    let image_data_uri = format!("data:image/png;base64,{}", BASE64_STANDARD.encode(png));
+
+    Ok(Challenge { token, image_data_uri })
+}
+
+/// Check `answer` against the challenge stored for `token`, case-insensitively.
+/// The entry is removed regardless of outcome, so a token can never be
+/// redeemed more than once.
+pub async fn verify_and_consume(token: &str, answer: &str) -> bool {
+    match storage::take(token).await {
+        Ok(Some(expected)) => expected == answer.trim().to_lowercase(),
+        _ => false,
+    }
+}
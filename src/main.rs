@@ -14,7 +14,7 @@ use axum::{
 use axum::{
     response::IntoResponse,
     response::Html,
-    extract::Multipart,
+    extract::ConnectInfo,
 };
 
 use axum::http::{
@@ -29,15 +29,36 @@ use tower_http::services::ServeDir;
 
 use ed25519_dalek::{SigningKey, Signer};
 
+use subtle::ConstantTimeEq;
+
+mod credentials;
+mod image_captcha;
+mod providers;
+mod request_token;
+mod services;
+mod storage;
 
 static RESPONSE_HEADER_CSP: &str = "default-src https:; base-uri 'none'; form-action https:; frame-ancestors 'none';";
 static RESPONSE_HEADER_X_FRAME_OPTIONS: &str = "DENY";
 static RESPONSE_HEADER_X_CONTENT_TYPE_OPTIONS: &str = "nosniff";
 
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, Debug)]
 struct ApiResponse {
     success: bool,
+    score: Option<f64>,
+    action: Option<String>,
+    hostname: Option<String>,
+    #[allow(dead_code)]
+    challenge_ts: Option<String>,
+    #[serde(rename = "error-codes", default)]
+    error_codes: Vec<String>,
+}
+
+/// Which CAPTCHA provider is currently active, read from `CAPTCHA_PROVIDER`.
+/// Defaults to `recaptcha` to preserve prior behavior.
+pub(crate) fn captcha_provider() -> String {
+    env::var("CAPTCHA_PROVIDER").unwrap_or_else(|_| "recaptcha".to_string())
 }
 
 
@@ -56,8 +77,50 @@ async fn handler_404() -> impl IntoResponse {
 }
 
 async fn handler_root() -> impl IntoResponse {
-    let recaptcha_site_key = env::var("RECAPTCHA_SITE_KEY").unwrap_or("".to_string());
-    let html = format!(r#"
+    let request_token = match request_token::issue().await {
+        Ok(request_token) => request_token,
+        Err(e) => {
+            eprintln!("Failed to issue request token: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to issue request token.").into_response();
+        }
+    };
+
+    let html = if captcha_provider() == "image" {
+        let challenge = match image_captcha::generate_challenge().await {
+            Ok(challenge) => challenge,
+            Err(e) => {
+                eprintln!("Failed to generate captcha challenge: {}", e);
+                return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to generate captcha challenge.").into_response();
+            }
+        };
+        format!(r#"
+<!DOCTYPE html>
+<html>
+    <head>
+    <meta charset="utf-8">
+    <title>CAPTCHA</title>
+    <meta name="viewport" content="width=device-width">
+    <link rel="stylesheet" href="/assets/main.css">
+    <script src="/assets/main.js"></script>
+    </head>
+    <body>
+    <form action="/submit" method="POST" enctype="multipart/form-data">
+        <img id="image-captcha-challenge" src="{image_data_uri}" alt="CAPTCHA challenge"/>
+        <input id="input-captcha-token" type="hidden" name="captcha-token" value="{captcha_token}"/>
+        <input id="input-captcha-answer" type="text" name="captcha-answer" autocomplete="off" required/>
+        <input id="input-request-token" type="hidden" name="request-token" value="{request_token}"/>
+        <input id="input-redirect-url" type="hidden" name="redirect-url"/>
+        <button id="button-submit" type="submit">Next</button>
+    </form>
+    </body>
+</html>
+"#, image_data_uri = challenge.image_data_uri, captcha_token = challenge.token, request_token = request_token)
+    } else {
+        let provider = providers::resolve(&captcha_provider()).expect("remote provider");
+        let site_key = env::var(provider.site_key_env_var()).unwrap_or("".to_string());
+        let script_tag = provider.script_tag();
+        let widget_html = provider.widget_html(&site_key);
+        format!(r#"
 <!DOCTYPE html>
 <html>
     <head>
@@ -66,24 +129,90 @@ async fn handler_root() -> impl IntoResponse {
     <meta name="viewport" content="width=device-width">
     <link rel="stylesheet" href="/assets/main.css">
     <script src="/assets/main.js"></script>
-    <script src="https://www.google.com/recaptcha/api.js" async defer></script>
+    {script_tag}
     </head>
     <body>
     <form action="/submit" method="POST" enctype="multipart/form-data">
-        <div class="g-recaptcha" data-sitekey="{recaptcha_site_key}" data-callback="captchaCallback"></div>
-        <input id="input-request-token" type="hidden" name="request-token"/>
+        {widget_html}
+        <input id="input-request-token" type="hidden" name="request-token" value="{request_token}"/>
         <input id="input-redirect-url" type="hidden" name="redirect-url"/>
         <button id="button-submit" type="submit" disabled>Next</button>
     </form>
     </body>
 </html>
-"#);
+"#, script_tag = script_tag, widget_html = widget_html, request_token = request_token)
+    };
     //
     (StatusCode::OK, Html(html)).into_response()
 }
 
+/// Issues a fresh image CAPTCHA challenge, for clients that want to reload
+/// the image without reloading the whole page.
+async fn handler_challenge() -> impl IntoResponse {
+    let challenge = match image_captcha::generate_challenge().await {
+        Ok(challenge) => challenge,
+        Err(e) => {
+            eprintln!("Failed to generate captcha challenge: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to generate captcha challenge.").into_response();
+        }
+    };
+    axum::Json(serde_json::json!({
+        "token": challenge.token,
+        "image": challenge.image_data_uri,
+    })).into_response()
+}
+
+/// Mints a fresh request-token. `handler_root` embeds one directly, but
+/// clients that manage a longer-lived page (e.g. a single-page app) can
+/// call this to get a new one once theirs expires.
+async fn handler_issue() -> impl IntoResponse {
+    match request_token::issue().await {
+        Ok(request_token) => axum::Json(serde_json::json!({
+            "request-token": request_token,
+        })).into_response(),
+        Err(e) => {
+            eprintln!("Failed to issue request token: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to issue request token.").into_response()
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct RegisterRequest {
+    url: String,
+}
+
+/// Onboards a downstream service, authorizing the origin of `url` as a
+/// `redirect-url` target. Guarded by the `ADMIN_SECRET` env var, which the
+/// caller must present via the `X-Admin-Secret` header.
+async fn handler_register(
+    headers: HeaderMap,
+    axum::Json(payload): axum::Json<RegisterRequest>,
+) -> impl IntoResponse {
+    let admin_secret = env::var("ADMIN_SECRET").unwrap_or_default();
+    if admin_secret.is_empty() {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Admin secret is not set.").into_response();
+    }
+
+    let provided_secret = headers.get("x-admin-secret").and_then(|v| v.to_str().ok()).unwrap_or("");
+    let secret_matches: bool = provided_secret.as_bytes().ct_eq(admin_secret.as_bytes()).into();
+    if !secret_matches {
+        return (StatusCode::UNAUTHORIZED, "Invalid admin secret.").into_response();
+    }
+
+    match services::register(&payload.url).await {
+        Ok(auth_token) => axum::Json(serde_json::json!({ "auth_token": auth_token })).into_response(),
+        Err(e) => {
+            eprintln!("Failed to register service: {}", e);
+            (StatusCode::BAD_REQUEST, "Failed to register service.").into_response()
+        }
+    }
+}
+
 async fn handler_submit(
-    mut multipart: Multipart,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    credentials: credentials::Credentials,
 ) -> impl IntoResponse {
     let private_key = env::var("PRIVATE_KEY").unwrap_or("".to_string());
     if private_key.is_empty() {
@@ -102,97 +231,152 @@ async fn handler_submit(
 
     let private_key: [u8; 32] = private_key[0..32].try_into().unwrap();
 
-    let recaptcha_secret = env::var("RECAPTCHA_SECRET").unwrap_or("".to_string());
-    if recaptcha_secret.is_empty() {
-        return (StatusCode::INTERNAL_SERVER_ERROR, "Recaptcha secret is not set.").into_response();
-    }
-
-    let mut request_token = None;
-    let mut redirect_url = None;
-    let mut recaptcha_response = None;
-    while let Ok(field) = multipart.next_field().await {
-        let field = if let Some(field) = field {
-            field
-        } else {
-            break;
-        };
-
-        let field_name = field.name().unwrap_or("");
-        let field_name = field_name.to_lowercase();
-        if field_name.is_empty() {
-            continue;
-        }
-
-        if field_name == "g-recaptcha-response" {
-            let response = field.text().await.unwrap_or("".to_string());
-            if response.is_empty() {
-                return (StatusCode::BAD_REQUEST, "Recaptcha response is empty.").into_response();
-            }
-            recaptcha_response = Some(response);
-        } else if field_name == "request-token" {
-            let token = field.text().await.unwrap_or("".to_string());
-            if token.is_empty() {
-                return (StatusCode::BAD_REQUEST, "Request token is empty.").into_response();
-            }
-            request_token = Some(token);
-        } else if field_name == "redirect-url" {
-            let url = field.text().await.unwrap_or("".to_string());
-            if url.is_empty() {
-                return (StatusCode::BAD_REQUEST, "Redirect URL is empty.").into_response();
-            }
-            redirect_url = Some(url);
-        }
-    }
+    let provider = captcha_provider();
+    let remote_provider = providers::resolve(&provider);
 
-    if let None = recaptcha_response {
-        return (StatusCode::BAD_REQUEST, "Recaptcha response is missing.").into_response();
-    }
+    let captcha_token = credentials.captcha_token;
+    let captcha_answer = credentials.captcha_answer;
+    let remote_response = credentials.captcha_response;
 
-    let request_token = if let Some(request_token) = request_token {
+    let request_token = if let Some(request_token) = credentials.request_token {
         request_token
     } else {
         return (StatusCode::BAD_REQUEST, "Request token is missing.").into_response();
     };
 
-    let redirect_url = if let Some(redirect_url) = redirect_url {
+    let redirect_url = if let Some(redirect_url) = credentials.redirect_url {
         redirect_url
     } else {
         return (StatusCode::BAD_REQUEST, "Redirect URL is missing.").into_response();
     };
 
-    let api_url = "https://www.google.com/recaptcha/api/siteverify";
+    match services::is_authorized(&redirect_url).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return (StatusCode::BAD_REQUEST, "Redirect URL is not authorized.").into_response();
+        }
+        Err(e) => {
+            eprintln!("Failed to check redirect URL authorization: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to check redirect URL authorization.").into_response();
+        }
+    }
 
-    let recaptcha_response = recaptcha_response.unwrap();
+    match request_token::exists(&request_token).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return (StatusCode::BAD_REQUEST, "Request token is invalid or has already been used.").into_response();
+        }
+        Err(e) => {
+            eprintln!("Failed to check request token: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to check request token.").into_response();
+        }
+    }
 
-    let api_params = (
-        ("secret", recaptcha_secret.as_str()),
-        ("response", recaptcha_response.as_str()),
-    );
-    
-    let client = reqwest::Client::new();
+    if provider == "image" {
+        let captcha_token = if let Some(captcha_token) = captcha_token {
+            captcha_token
+        } else {
+            return (StatusCode::BAD_REQUEST, "Captcha token is missing.").into_response();
+        };
 
-    let response = client.post(api_url)
-        .form(&api_params)
-        .send()
-        .await;
+        let captcha_answer = if let Some(captcha_answer) = captcha_answer {
+            captcha_answer
+        } else {
+            return (StatusCode::BAD_REQUEST, "Captcha answer is missing.").into_response();
+        };
 
-    let response = match response {
-        Ok(response) => response,
-        Err(e) => {
-            eprintln!("Recaptcha request failed: {}", e);
-            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Recaptcha request failed: {}", e)).into_response();
+        if !image_captcha::verify_and_consume(&captcha_token, &captcha_answer).await {
+            return (StatusCode::BAD_REQUEST, "Captcha answer is incorrect.").into_response();
         }
-    };
-
-    let response = if let Ok(res) = response.json::<ApiResponse>().await {
-        res
     } else {
-        return (StatusCode::INTERNAL_SERVER_ERROR, "Recaptcha response is invalid.").into_response();
-    };
+        let remote_provider = remote_provider.expect("remote provider");
+
+        let remote_response = if let Some(remote_response) = remote_response {
+            remote_response
+        } else {
+            return (StatusCode::BAD_REQUEST, "Captcha response is missing.").into_response();
+        };
+
+        let secret = env::var(remote_provider.secret_env_var()).unwrap_or("".to_string());
+        if secret.is_empty() {
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Captcha secret is not set.").into_response();
+        }
+
+        let remote_ip = remote_addr.ip().to_string();
+        let api_params = (
+            ("secret", secret.as_str()),
+            ("response", remote_response.as_str()),
+            ("remoteip", remote_ip.as_str()),
+        );
+
+        let client = reqwest::Client::new();
+
+        let response = client.post(remote_provider.siteverify_url())
+            .form(&api_params)
+            .send()
+            .await;
+
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!("Siteverify request failed: {}", e);
+                return (StatusCode::INTERNAL_SERVER_ERROR, format!("Siteverify request failed: {}", e)).into_response();
+            }
+        };
+
+        let response = if let Ok(res) = response.json::<ApiResponse>().await {
+            res
+        } else {
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Siteverify response is invalid.").into_response();
+        };
+
+        if !response.success {
+            eprintln!("Siteverify failed, error codes: {:?}", response.error_codes);
+            return (StatusCode::BAD_REQUEST, "Captcha response is invalid.").into_response();
+        }
+
+        let allowed_hostnames = env::var("CAPTCHA_ALLOWED_HOSTNAMES").unwrap_or_default();
+        if !allowed_hostnames.is_empty() {
+            let hostname = response.hostname.as_deref().unwrap_or("");
+            let is_allowed = allowed_hostnames.split(',').map(|h| h.trim()).any(|h| h == hostname);
+            if !is_allowed {
+                eprintln!("Siteverify hostname \"{}\" is not in CAPTCHA_ALLOWED_HOSTNAMES.", hostname);
+                return (StatusCode::BAD_REQUEST, "Captcha response hostname is not allowed.").into_response();
+            }
+        }
+
+        if provider == "recaptcha" {
+            if let Ok(min_score) = env::var("RECAPTCHA_MIN_SCORE") {
+                let min_score: f64 = match min_score.parse() {
+                    Ok(min_score) => min_score,
+                    Err(_) => {
+                        return (StatusCode::INTERNAL_SERVER_ERROR, "RECAPTCHA_MIN_SCORE is not a valid number.").into_response();
+                    }
+                };
+                let score = response.score.unwrap_or(0.0);
+                if score < min_score {
+                    eprintln!("Recaptcha score {} is below the configured minimum {}.", score, min_score);
+                    return (StatusCode::BAD_REQUEST, "Captcha score is too low.").into_response();
+                }
+            }
 
-    eprintln!("Recaptcha response: {:?}", response.success);
-    if !response.success {
-        return (StatusCode::BAD_REQUEST, "Recaptcha response is invalid.").into_response();
+            let expected_action = env::var("RECAPTCHA_EXPECTED_ACTION").unwrap_or_default();
+            if !expected_action.is_empty() && response.action.as_deref() != Some(expected_action.as_str()) {
+                eprintln!("Recaptcha action {:?} does not match expected action \"{}\".", response.action, expected_action);
+                return (StatusCode::BAD_REQUEST, "Captcha action does not match.").into_response();
+            }
+        }
+    }
+
+    let token_redeemed = match request_token::redeem(&request_token).await {
+        Ok(redeemed) => redeemed,
+        Err(e) => {
+            eprintln!("Failed to redeem request token: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to redeem request token.").into_response();
+        }
+    };
+    if !token_redeemed {
+        return (StatusCode::BAD_REQUEST, "Request token is invalid or has already been used.").into_response();
     }
 
     let message = request_token.as_bytes();
@@ -201,6 +385,17 @@ async fn handler_submit(
     let signature = signing_key.sign(message);
     let signature = hex::encode(signature.to_bytes());
 
+    let wants_json = headers.get(axum::http::header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/json"));
+
+    if wants_json {
+        return axum::Json(serde_json::json!({
+            "request-token": request_token,
+            "signature": signature,
+        })).into_response();
+    }
+
     let redirect_url = format!("{}?request-token={}&signature={}", redirect_url, request_token, signature);
     let mut header_map = HeaderMap::new();
     header_map.insert(HeaderName::from_static("location"), redirect_url.parse().unwrap());
@@ -227,6 +422,12 @@ async fn main() -> anyhow::Result<()> {
 
         .route("/submit", post(handler_submit))
 
+        .route("/challenge", get(handler_challenge))
+
+        .route("/issue", get(handler_issue))
+
+        .route("/register", post(handler_register))
+
         // assets directories
         .nest_service("/assets", ServeDir::new("assets"))
 
@@ -238,7 +439,10 @@ async fn main() -> anyhow::Result<()> {
 
     // run server
     let listener = tokio::net::TcpListener::bind(&addr).await?;
-    let server = axum::serve(listener, app);
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    );
 
     println!("Listening on http://{}", &addr);
 
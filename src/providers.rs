@@ -0,0 +1,97 @@
+//! Third-party CAPTCHA providers verified via a remote `siteverify` call.
+
+pub trait RemoteProvider: Send + Sync {
+    /// Env var holding this provider's public site key.
+    fn site_key_env_var(&self) -> &'static str;
+
+    /// Env var holding this provider's verification secret.
+    fn secret_env_var(&self) -> &'static str;
+
+    /// `<script>` tag needed to render the widget.
+    fn script_tag(&self) -> &'static str;
+
+    /// Widget markup for the given site key.
+    fn widget_html(&self, site_key: &str) -> String;
+
+    /// The `siteverify` endpoint to POST `secret` and `response` to.
+    fn siteverify_url(&self) -> &'static str;
+
+    /// Name of the form field the client-side widget populates.
+    fn response_field_name(&self) -> &'static str;
+}
+
+pub struct Recaptcha;
+pub struct HCaptcha;
+pub struct Turnstile;
+
+impl RemoteProvider for Recaptcha {
+    fn site_key_env_var(&self) -> &'static str { "RECAPTCHA_SITE_KEY" }
+
+    fn secret_env_var(&self) -> &'static str { "RECAPTCHA_SECRET" }
+
+    fn script_tag(&self) -> &'static str {
+        r#"<script src="https://www.google.com/recaptcha/api.js" async defer></script>"#
+    }
+
+    fn widget_html(&self, site_key: &str) -> String {
+        format!(r#"<div class="g-recaptcha" data-sitekey="{site_key}" data-callback="captchaCallback"></div>"#)
+    }
+
+    fn siteverify_url(&self) -> &'static str { "https://www.google.com/recaptcha/api/siteverify" }
+
+    fn response_field_name(&self) -> &'static str { "g-recaptcha-response" }
+}
+
+impl RemoteProvider for HCaptcha {
+    fn site_key_env_var(&self) -> &'static str { "HCAPTCHA_SITE_KEY" }
+
+    fn secret_env_var(&self) -> &'static str { "HCAPTCHA_SECRET" }
+
+    fn script_tag(&self) -> &'static str {
+        r#"<script src="https://hcaptcha.com/1/api.js" async defer></script>"#
+    }
+
+    fn widget_html(&self, site_key: &str) -> String {
+        format!(r#"<div class="h-captcha" data-sitekey="{site_key}" data-callback="captchaCallback"></div>"#)
+    }
+
+    fn siteverify_url(&self) -> &'static str { "https://api.hcaptcha.com/siteverify" }
+
+    fn response_field_name(&self) -> &'static str { "h-captcha-response" }
+}
+
+impl RemoteProvider for Turnstile {
+    fn site_key_env_var(&self) -> &'static str { "TURNSTILE_SITE_KEY" }
+
+    fn secret_env_var(&self) -> &'static str { "TURNSTILE_SECRET" }
+
+    fn script_tag(&self) -> &'static str {
+        r#"<script src="https://challenges.cloudflare.com/turnstile/v0/api.js" async defer></script>"#
+    }
+
+    fn widget_html(&self, site_key: &str) -> String {
+        format!(r#"<div class="cf-turnstile" data-sitekey="{site_key}" data-callback="captchaCallback"></div>"#)
+    }
+
+    fn siteverify_url(&self) -> &'static str { "https://challenges.cloudflare.com/turnstile/v0/siteverify" }
+
+    fn response_field_name(&self) -> &'static str { "cf-turnstile-response" }
+}
+
+/// Resolve the active remote provider from the value of `CAPTCHA_PROVIDER`,
+/// falling back to reCAPTCHA. Returns `None` for `"image"`, since that mode
+/// is handled by `image_captcha` instead of a remote provider.
+pub fn resolve(name: &str) -> Option<Box<dyn RemoteProvider>> {
+    match name {
+        "image" => None,
+        "hcaptcha" => Some(Box::new(HCaptcha)),
+        "turnstile" => Some(Box::new(Turnstile)),
+        _ => Some(Box::new(Recaptcha)),
+    }
+}
+
+/// The response field name of the currently configured provider, read from
+/// `CAPTCHA_PROVIDER`. `None` in `image` mode.
+pub fn active_response_field_name() -> Option<&'static str> {
+    resolve(&crate::captcha_provider()).map(|provider| provider.response_field_name())
+}
@@ -0,0 +1,31 @@
+//! Issuance and single-use redemption of `request-token`s.
+
+use std::time::Duration;
+
+use crate::storage;
+
+/// How long an issued request-token remains redeemable.
+const TOKEN_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Marker value stored for an issued token; only its presence matters.
+const ISSUED_MARKER: &str = "issued";
+
+/// Mint a new request-token and remember that this server issued it.
+pub async fn issue() -> anyhow::Result<String> {
+    let token = storage::random_token();
+    storage::STORAGE.store(&token, ISSUED_MARKER, TOKEN_TTL).await?;
+    Ok(token)
+}
+
+/// Check that `token` was issued by this server, without consuming it. Lets
+/// callers reject a bogus token cheaply before doing expensive verification
+/// work; [`redeem`] remains the actual single-use enforcement.
+pub async fn exists(token: &str) -> anyhow::Result<bool> {
+    Ok(storage::STORAGE.get(token).await?.is_some())
+}
+
+/// Check that `token` was issued by this server, consuming it so it cannot
+/// be redeemed a second time.
+pub async fn redeem(token: &str) -> anyhow::Result<bool> {
+    Ok(storage::take(token).await?.is_some())
+}
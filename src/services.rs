@@ -0,0 +1,48 @@
+//! Registry of downstream services authorized to receive a signed redirect,
+//! keyed by the origin of their registered `redirect-url`.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::storage;
+
+/// Registered services don't expire on their own; this is simply long
+/// enough that `CaptchaStorage`'s TTL model doesn't get in the way.
+const SERVICE_TTL: Duration = Duration::from_secs(10 * 365 * 24 * 60 * 60);
+
+#[derive(Serialize, Deserialize)]
+struct RegisteredService {
+    auth_token: String,
+}
+
+/// Register `url`'s origin as an authorized redirect target, returning a
+/// fresh `auth_token` identifying the registration.
+pub async fn register(url: &str) -> anyhow::Result<String> {
+    let origin = origin_of(url)?;
+    let auth_token = storage::random_token();
+    let service = RegisteredService { auth_token: auth_token.clone() };
+    storage::STORAGE
+        .store(&registry_key(&origin), &serde_json::to_string(&service)?, SERVICE_TTL)
+        .await?;
+    Ok(auth_token)
+}
+
+/// Check whether `redirect_url`'s origin has been registered.
+pub async fn is_authorized(redirect_url: &str) -> anyhow::Result<bool> {
+    let origin = match origin_of(redirect_url) {
+        Ok(origin) => origin,
+        Err(_) => return Ok(false),
+    };
+    Ok(storage::STORAGE.get(&registry_key(&origin)).await?.is_some())
+}
+
+fn registry_key(origin: &str) -> String {
+    format!("service-origin:{}", origin)
+}
+
+fn origin_of(url: &str) -> anyhow::Result<String> {
+    let parsed = Url::parse(url)?;
+    Ok(parsed.origin().ascii_serialization())
+}
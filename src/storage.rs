@@ -0,0 +1,60 @@
+//! Pluggable storage for CAPTCHA state: short-lived issued request-tokens
+//! and, in image-provider mode, challenge answers, plus the long-lived
+//! registrations of downstream services.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use rand::RngCore;
+
+mod memory;
+pub use memory::MemoryStorage;
+
+#[cfg(feature = "cacache-storage")]
+mod cacache_store;
+#[cfg(feature = "cacache-storage")]
+pub use cacache_store::CacacheStorage;
+
+#[async_trait]
+pub trait CaptchaStorage: Send + Sync {
+    /// Remember `value` under `token` for up to `ttl`.
+    async fn store(&self, token: &str, value: &str, ttl: Duration) -> anyhow::Result<()>;
+
+    /// Look up the value stored for `token`, without consuming it.
+    async fn get(&self, token: &str) -> anyhow::Result<Option<String>>;
+
+    /// Remove any entry stored for `token`.
+    async fn clear(&self, token: &str) -> anyhow::Result<()>;
+
+    /// Atomically fetch and remove the value stored for `token`, so
+    /// concurrent callers can never redeem the same token twice.
+    async fn take(&self, token: &str) -> anyhow::Result<Option<String>>;
+}
+
+/// The active storage backend, chosen at compile time via the
+/// `cacache-storage` feature. Defaults to the in-memory store.
+pub static STORAGE: Lazy<Box<dyn CaptchaStorage>> = Lazy::new(build_storage);
+
+#[cfg(feature = "cacache-storage")]
+fn build_storage() -> Box<dyn CaptchaStorage> {
+    Box::new(CacacheStorage::new("./captcha-storage"))
+}
+
+#[cfg(not(feature = "cacache-storage"))]
+fn build_storage() -> Box<dyn CaptchaStorage> {
+    Box::new(MemoryStorage::new())
+}
+
+/// Fetch and remove the value stored for `token`, if any, so it cannot be
+/// redeemed a second time.
+pub async fn take(token: &str) -> anyhow::Result<Option<String>> {
+    STORAGE.take(token).await
+}
+
+/// Generate a fresh opaque token: 16 random bytes, hex-encoded.
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
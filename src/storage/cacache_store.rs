@@ -0,0 +1,107 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use super::CaptchaStorage;
+
+/// Disk-backed [`CaptchaStorage`] using `cacache`, for deployments that want
+/// issued tokens to survive a restart. Enabled via the `cacache-storage`
+/// feature.
+///
+/// `cacache` has no atomic read-and-remove, so `take` serializes through
+/// `redeem_lock` to keep redemption single-use within this process.
+pub struct CacacheStorage {
+    root: PathBuf,
+    redeem_lock: Mutex<()>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    value: String,
+    expires_at_unix: u64,
+}
+
+impl CacacheStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into(), redeem_lock: Mutex::new(()) }
+    }
+
+    async fn read_live(&self, token: &str) -> anyhow::Result<Option<String>> {
+        let data = match cacache::read(&self.root, token).await {
+            Ok(data) => data,
+            Err(cacache::Error::EntryNotFound(..)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let entry: Entry = serde_json::from_slice(&data)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        if now >= entry.expires_at_unix {
+            cacache::remove(&self.root, token).await?;
+            return Ok(None);
+        }
+
+        Ok(Some(entry.value))
+    }
+}
+
+#[async_trait]
+impl CaptchaStorage for CacacheStorage {
+    async fn store(&self, token: &str, value: &str, ttl: Duration) -> anyhow::Result<()> {
+        let expires_at_unix = (SystemTime::now() + ttl)
+            .duration_since(UNIX_EPOCH)?
+            .as_secs();
+        let entry = Entry { value: value.to_string(), expires_at_unix };
+        cacache::write(&self.root, token, serde_json::to_vec(&entry)?).await?;
+        Ok(())
+    }
+
+    async fn get(&self, token: &str) -> anyhow::Result<Option<String>> {
+        self.read_live(token).await
+    }
+
+    async fn clear(&self, token: &str) -> anyhow::Result<()> {
+        cacache::remove(&self.root, token).await?;
+        Ok(())
+    }
+
+    async fn take(&self, token: &str) -> anyhow::Result<Option<String>> {
+        let _guard = self.redeem_lock.lock().await;
+        let value = self.read_live(token).await?;
+        if value.is_some() {
+            cacache::remove(&self.root, token).await?;
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn take_redeems_a_concurrently_contested_token_exactly_once() {
+        let root = std::env::temp_dir().join(format!("captcha-server-rs-test-{}", std::process::id()));
+        let storage = Arc::new(CacacheStorage::new(root));
+        storage.store("token", "value", Duration::from_secs(60)).await.unwrap();
+
+        let mut attempts = Vec::new();
+        for _ in 0..16 {
+            let storage = Arc::clone(&storage);
+            attempts.push(tokio::spawn(async move { storage.take("token").await.unwrap() }));
+        }
+
+        let mut successes = 0;
+        for attempt in attempts {
+            if attempt.await.unwrap().is_some() {
+                successes += 1;
+            }
+        }
+
+        assert_eq!(successes, 1);
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+
+use super::CaptchaStorage;
+
+/// In-memory [`CaptchaStorage`], backed by a `DashMap` and sweeping expired
+/// entries on each `store` and `get`; `take` skips an expired entry without
+/// sweeping. State does not survive a restart, which is fine
+/// for a single-instance deployment.
+pub struct MemoryStorage {
+    entries: DashMap<String, (String, Instant)>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self { entries: DashMap::new() }
+    }
+
+    fn sweep(&self) {
+        let now = Instant::now();
+        self.entries.retain(|_, (_, expires_at)| *expires_at > now);
+    }
+}
+
+#[async_trait]
+impl CaptchaStorage for MemoryStorage {
+    async fn store(&self, token: &str, value: &str, ttl: Duration) -> anyhow::Result<()> {
+        self.sweep();
+        self.entries.insert(token.to_string(), (value.to_string(), Instant::now() + ttl));
+        Ok(())
+    }
+
+    async fn get(&self, token: &str) -> anyhow::Result<Option<String>> {
+        self.sweep();
+        Ok(self.entries.get(token).map(|entry| entry.0.clone()))
+    }
+
+    async fn clear(&self, token: &str) -> anyhow::Result<()> {
+        self.entries.remove(token);
+        Ok(())
+    }
+
+    async fn take(&self, token: &str) -> anyhow::Result<Option<String>> {
+        let now = Instant::now();
+        Ok(self.entries
+            .remove_if(token, |_, (_, expires_at)| *expires_at > now)
+            .map(|(_, (value, _))| value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn take_redeems_a_concurrently_contested_token_exactly_once() {
+        let storage = Arc::new(MemoryStorage::new());
+        storage.store("token", "value", Duration::from_secs(60)).await.unwrap();
+
+        let mut attempts = Vec::new();
+        for _ in 0..16 {
+            let storage = Arc::clone(&storage);
+            attempts.push(tokio::spawn(async move { storage.take("token").await.unwrap() }));
+        }
+
+        let mut successes = 0;
+        for attempt in attempts {
+            if attempt.await.unwrap().is_some() {
+                successes += 1;
+            }
+        }
+
+        assert_eq!(successes, 1);
+    }
+}